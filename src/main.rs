@@ -1,173 +1,965 @@
 use std::error::Error;
+use std::io::Write;
+use std::sync::mpsc::{self, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::collections::VecDeque;
-use std::time::Instant;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-use bluest::{Adapter, AdvertisingDevice};
+use bluest::{Adapter, AdvertisingDevice, Device};
 use futures_lite::stream::StreamExt;
+use rusqlite::Connection;
 use tiny_http::{Server, Response, Header};
 use serde::Serialize;
+use uuid::{uuid, Uuid};
 
-// 共享数据结构存储心率信息
-struct HeartRateMonitor {
+// 历史数据库文件路径
+const HISTORY_DB_PATH: &str = "heart_rate_history.db";
+// /history 单次查询最多返回的样本数，超出时按固定步长下采样
+const HISTORY_DOWNSAMPLE_CAP: usize = 500;
+
+// 所有已连接的SSE客户端，每个客户端对应一个发送通道
+type EventSenders = Arc<Mutex<Vec<Sender<String>>>>;
+
+// 已经建立GATT连接（不再需要回退到广播解析）的设备地址集合
+type GattConnected = Arc<Mutex<HashSet<String>>>;
+
+// 已经尝试过GATT连接的设备地址，连接子任务退出后会自行移除，以便重连后可以再次尝试
+type GattAttempted = Arc<Mutex<HashSet<String>>>;
+
+// 历史数据持久化存储，底层是一个共享的SQLite连接
+type Storage = Arc<Mutex<Connection>>;
+
+// 适配器/扫描断线重连后的等待时长序列，超出部分固定按最后一档（30秒）等待
+const RECONNECT_BACKOFF_SECS: [u64; 4] = [1, 5, 10, 30];
+
+// 向SSE客户端推送状态心跳的间隔，确保重连状态与基于时间推算的"信号丢失"
+// 即使没有新的心率通知也能及时反映到已连接的前端页面
+const STATUS_TICK_SECS: u64 = 5;
+
+// 判定一次读数是否"最近"的时间窗口：既用于/data、/events展示的"信号丢失"，
+// 也用于重连状态覆盖——仍在此窗口内更新的设备视为正常，不应被标成"重连中…"
+const DEVICE_RECENT_SECS: u64 = 10;
+
+// 是否正处于重连状态，供 /data 与 /events 覆盖展示的status/status_color
+type ReconnectState = Arc<Mutex<bool>>;
+
+#[derive(Serialize)]
+struct HistorySample {
+    device_id: String,
+    bpm: u8,
+    rssi: i16,
+    timestamp: i64,
+}
+
+// 标准心率服务与心率测量特征值（蓝牙SIG分配号 0x180D / 0x2A37）
+const HEART_RATE_SERVICE: Uuid = uuid!("0000180d-0000-1000-8000-00805f9b34fb");
+const HEART_RATE_MEASUREMENT: Uuid = uuid!("00002a37-0000-1000-8000-00805f9b34fb");
+// 标准电量服务与电量特征值（0x180F / 0x2A19）
+const BATTERY_SERVICE: Uuid = uuid!("0000180f-0000-1000-8000-00805f9b34fb");
+const BATTERY_LEVEL: Uuid = uuid!("00002a19-0000-1000-8000-00805f9b34fb");
+
+// 单个设备的心率状态
+struct DeviceState {
     current_rate: u8,
     device_name: String,
     rssi: i16,
+    battery: u8,
     last_update: Instant,
     history: VecDeque<u8>,
+    in_alarm: bool,
+    alarm_reason: String,
 }
 
 #[derive(Serialize)]
 struct HeartRateUpdate {
+    device_id: String,
     heart_rate: u8,
     device_name: String,
     rssi: i16,
+    battery: u8,
     elapsed_secs: u64,
     status: String,
     status_color: String,
     history: Vec<u8>,
+    alarm: bool,
+    alarm_reason: String,
 }
 
-impl HeartRateMonitor {
+// /data与/events推送给前端的顶层负载：reconnecting独立于devices数组存在，
+// 这样适配器在还没发现任何设备时掉线，前端也能感知到"重连中"，而不必依赖某个设备的状态
+#[derive(Serialize)]
+struct StatusPayload {
+    reconnecting: bool,
+    devices: Vec<HeartRateUpdate>,
+}
+
+impl DeviceState {
     fn new() -> Self {
         Self {
             current_rate: 0,
             device_name: "等待连接...".to_string(),
             rssi: i16::MIN,
+            battery: 0,
             last_update: Instant::now(),
             history: VecDeque::with_capacity(60),
+            in_alarm: false,
+            alarm_reason: String::new(),
         }
     }
-    
-    fn update(&mut self, rate: u8, name: &str, rssi: i16) {
+
+    // 更新读数并根据阈值评估报警状态。仅在从"未报警"进入"报警"状态的那一次
+    // 返回Some(reason)，持续超限的后续读数不会重复触发。
+    fn update(
+        &mut self,
+        rate: u8,
+        name: &str,
+        rssi: i16,
+        battery: Option<u8>,
+        alarm: &AlarmConfig,
+    ) -> Option<String> {
         self.current_rate = rate;
         self.device_name = name.to_string();
         self.rssi = rssi;
+        if let Some(battery) = battery {
+            self.battery = battery;
+        }
         self.last_update = Instant::now();
-        
+
         // 更新历史数据
         self.history.push_back(rate);
         if self.history.len() > 60 {
             self.history.pop_front();
         }
+
+        let reason = alarm.check(rate);
+        let newly_triggered = reason.is_some() && !self.in_alarm;
+        self.in_alarm = reason.is_some();
+        self.alarm_reason = reason.clone().unwrap_or_default();
+
+        if newly_triggered {
+            reason
+        } else {
+            None
+        }
     }
-    
+
     fn is_recent(&self) -> bool {
-        self.last_update.elapsed().as_secs() < 10
+        self.last_update.elapsed().as_secs() < DEVICE_RECENT_SECS
+    }
+}
+
+// 按设备地址跟踪所有正在被监测的设备，取代原先只支持单个设备的结构
+struct DeviceRegistry {
+    devices: BTreeMap<String, DeviceState>,
+}
+
+impl DeviceRegistry {
+    fn new() -> Self {
+        Self {
+            devices: BTreeMap::new(),
+        }
+    }
+
+    // 返回值与DeviceState::update一致：仅在新进入报警状态时为Some(reason)
+    fn update(
+        &mut self,
+        address: &str,
+        rate: u8,
+        name: &str,
+        rssi: i16,
+        battery: Option<u8>,
+        alarm: &AlarmConfig,
+    ) -> Option<String> {
+        self.devices
+            .entry(address.to_string())
+            .or_insert_with(DeviceState::new)
+            .update(rate, name, rssi, battery, alarm)
+    }
+}
+
+// 高/低心率报警阈值，可通过环境变量配置；越界时可选向webhook推送提醒
+struct AlarmConfig {
+    low: u8,
+    high: u8,
+    webhook: Option<String>,
+}
+
+impl AlarmConfig {
+    fn from_env() -> Self {
+        let low = std::env::var("HR_ALARM_LOW")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(40);
+        let high = std::env::var("HR_ALARM_HIGH")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(140);
+        let webhook = std::env::var("HR_ALARM_WEBHOOK")
+            .ok()
+            .filter(|url| !url.is_empty());
+        Self { low, high, webhook }
+    }
+
+    fn check(&self, rate: u8) -> Option<String> {
+        if rate < self.low {
+            Some(format!("心率过低: {rate} < {low}", low = self.low))
+        } else if rate > self.high {
+            Some(format!("心率过高: {rate} > {high}", high = self.high))
+        } else {
+            None
+        }
+    }
+}
+
+// 向配置的webhook地址发送报警通知，在独立线程上进行，不阻塞调用方
+fn fire_webhook(webhook: &Option<String>, device_id: &str, name: &str, rate: u8, reason: &str) {
+    let Some(url) = webhook.clone() else {
+        return;
+    };
+    let device_id = device_id.to_string();
+    let name = name.to_string();
+    let reason = reason.to_string();
+    thread::spawn(move || {
+        let payload = serde_json::json!({
+            "device_id": device_id,
+            "device_name": name,
+            "heart_rate": rate,
+            "reason": reason,
+        });
+        if let Err(err) = ureq::post(&url).send_json(payload) {
+            println!("报警webhook推送失败: {err}");
+        }
+    });
+}
+
+// MQTT代理连接参数；未设置HR_MQTT_HOST时保持为None，发布端随之成为no-op
+struct MqttConfig {
+    host: String,
+    port: u16,
+    topic_prefix: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl MqttConfig {
+    fn from_env() -> Option<Self> {
+        let host = std::env::var("HR_MQTT_HOST").ok().filter(|v| !v.is_empty())?;
+        let port = std::env::var("HR_MQTT_PORT")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(1883);
+        let topic_prefix =
+            std::env::var("HR_MQTT_TOPIC_PREFIX").unwrap_or_else(|_| "heart-rate".to_string());
+        let username = std::env::var("HR_MQTT_USERNAME").ok();
+        let password = std::env::var("HR_MQTT_PASSWORD").ok();
+        Some(Self {
+            host,
+            port,
+            topic_prefix,
+            username,
+            password,
+        })
+    }
+}
+
+// 向MQTT代理发布心率读数；未配置代理时client为None，publish变为no-op
+struct MqttPublisher {
+    client: Option<rumqttc::Client>,
+    topic_prefix: String,
+}
+
+impl MqttPublisher {
+    fn connect(config: Option<MqttConfig>) -> Self {
+        let Some(config) = config else {
+            return Self {
+                client: None,
+                topic_prefix: String::new(),
+            };
+        };
+
+        let mut options = rumqttc::MqttOptions::new("mi-band-heart-rate", config.host.clone(), config.port);
+        options.set_keep_alive(std::time::Duration::from_secs(30));
+        if let (Some(username), Some(password)) = (&config.username, &config.password) {
+            options.set_credentials(username.clone(), password.clone());
+        }
+
+        let (client, mut connection) = rumqttc::Client::new(options, 10);
+
+        // MQTT事件循环运行在独立线程上，与HTTP服务器线程并列
+        thread::spawn(move || {
+            for notification in connection.iter() {
+                if let Err(err) = notification {
+                    println!("MQTT连接出现问题: {err}");
+                }
+            }
+        });
+
+        println!("已连接MQTT代理 {}:{}", config.host, config.port);
+        Self {
+            client: Some(client),
+            topic_prefix: config.topic_prefix,
+        }
+    }
+
+    fn publish(&self, device_id: &str, update: &HeartRateUpdate) {
+        let Some(client) = &self.client else {
+            return;
+        };
+
+        let topic = format!("{}/{}/heart_rate", self.topic_prefix, device_id);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let payload = serde_json::json!({
+            "bpm": update.heart_rate,
+            "rssi": update.rssi,
+            "battery": update.battery,
+            "timestamp": timestamp,
+        });
+        let body = serde_json::to_vec(&payload).unwrap();
+
+        // 发布为fire-and-forget：用try_publish避免代理不可达时阻塞在满队列上拖住采集主流程，
+        // 队列满时直接丢弃本次读数，下一次读数会在稍后重试
+        if let Err(err) = client.try_publish(topic, rumqttc::QoS::AtMostOnce, true, body) {
+            println!("MQTT发布失败: {err}");
+        }
     }
 }
 
+// 控制哪些广播设备会被当作心率带处理；默认只保留原先硬编码的小米手环4，
+// 可通过环境变量或命令行参数放宽为任意厂商ID/设备名，或接受所有设备
+struct ScanFilter {
+    company_id: Option<u16>,
+    device_name: Option<String>,
+    accept_all: bool,
+}
+
+impl ScanFilter {
+    fn from_env_and_args() -> Self {
+        let mut company_id = Some(0x0157u16);
+        let mut device_name = Some("Mi Smart Band 4".to_string());
+        let mut accept_all = false;
+
+        if let Ok(value) = std::env::var("HR_COMPANY_ID") {
+            company_id = parse_company_id(&value);
+        }
+        if let Ok(value) = std::env::var("HR_DEVICE_NAME") {
+            device_name = Some(value);
+        }
+        if is_truthy_env("HR_ACCEPT_ALL") {
+            accept_all = true;
+        }
+
+        for arg in std::env::args().skip(1) {
+            if let Some(value) = arg.strip_prefix("--company-id=") {
+                company_id = parse_company_id(value);
+            } else if let Some(value) = arg.strip_prefix("--device-name=") {
+                device_name = Some(value.to_string());
+            } else if arg == "--accept-all" {
+                accept_all = true;
+            }
+        }
+
+        if accept_all {
+            company_id = None;
+            device_name = None;
+        }
+
+        Self {
+            company_id,
+            device_name,
+            accept_all,
+        }
+    }
+
+    fn matches(&self, company_id: u16, name: &str) -> bool {
+        if self.accept_all {
+            return true;
+        }
+        let company_ok = self.company_id.map_or(true, |expected| expected == company_id);
+        let name_ok = self.device_name.as_deref().map_or(true, |expected| expected == name);
+        company_ok && name_ok
+    }
+}
+
+// 解析 HR_COMPANY_ID / --company-id=，支持十进制或"0x"前缀的十六进制
+fn parse_company_id(value: &str) -> Option<u16> {
+    let trimmed = value.trim();
+    match trimmed.strip_prefix("0x").or_else(|| trimmed.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => trimmed.parse().ok(),
+    }
+}
+
+fn is_truthy_env(key: &str) -> bool {
+    std::env::var(key)
+        .map(|value| matches!(value.as_str(), "1" | "true" | "True" | "TRUE"))
+        .unwrap_or(false)
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    // 创建共享心率监视器
-    let heart_rate = Arc::new(Mutex::new(HeartRateMonitor::new()));
-    
+    // 创建共享设备注册表，按设备地址跟踪每一个正在监测的心率带
+    let heart_rate = Arc::new(Mutex::new(DeviceRegistry::new()));
+    // 创建共享的SSE客户端列表
+    let event_senders: EventSenders = Arc::new(Mutex::new(Vec::new()));
+    // 已经通过GATT连接上的设备地址，这些设备不再需要广播解析兜底
+    let gatt_connected: GattConnected = Arc::new(Mutex::new(HashSet::new()));
+    // 已经尝试过GATT连接的设备地址，避免每次广播都重新发起连接
+    let gatt_attempted: GattAttempted = Arc::new(Mutex::new(HashSet::new()));
+    // 控制哪些设备会被当作心率带处理（默认只认小米手环4，可通过环境变量/参数放宽）
+    let filter = ScanFilter::from_env_and_args();
+    // 打开历史数据库，所有读数都会追加写入其中
+    let storage = open_storage()?;
+    // 高/低心率报警阈值与可选的webhook通知地址
+    let alarm_config = Arc::new(AlarmConfig::from_env());
+    // 可选的MQTT发布端；未配置代理时publish调用为no-op
+    let mqtt = Arc::new(MqttPublisher::connect(MqttConfig::from_env()));
+    // 适配器/扫描是否正在重连，供HTTP端点覆盖展示状态
+    let reconnecting: ReconnectState = Arc::new(Mutex::new(false));
+
     // 启动HTTP服务器线程
     let hr_web = heart_rate.clone();
+    let events_web = event_senders.clone();
+    let history_web = storage.clone();
+    let reconnecting_web = reconnecting.clone();
     thread::spawn(move || {
-        start_http_server(hr_web);
+        start_http_server(hr_web, events_web, history_web, reconnecting_web);
     });
 
-    let adapter = Adapter::default()
-        .await
-        .ok_or("蓝牙设备未找到...")?;
-    adapter.wait_available().await?;
+    // 定期向已连接的SSE客户端推送一次当前状态，这样重连状态和基于时间推算的"信号丢失"
+    // 都能在没有新心率数据时也被已订阅的客户端感知到，而不必等待下一次扫描/通知
+    let hr_tick = heart_rate.clone();
+    let events_tick = event_senders.clone();
+    let reconnecting_tick = reconnecting.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(STATUS_TICK_SECS)).await;
+            let payload = {
+                let registry = hr_tick.lock().unwrap();
+                build_status_payload(&registry, *reconnecting_tick.lock().unwrap())
+            };
+            broadcast_updates(&events_tick, &payload);
+        }
+    });
 
-    println!("开始扫描在线的小米设备...");
+    println!("开始扫描在线设备...");
     println!("请访问: http://localhost:8080/ 查看心率监测");
-    
-    let mut scan = adapter.scan(&[]).await?;
-
-    while let Some(discovered_device) = scan.next().await {
-        // 使用闭包捕获共享心率监视器
-        let hr_monitor = heart_rate.clone();
-        handle_device(discovered_device, move |rate, name, rssi| {
-            let mut monitor = hr_monitor.lock().unwrap();
-            monitor.update(rate, name, rssi);
-        });
+
+    // 连续失败次数，用于在[1s, 5s, 10s, 30s]之间选取等待时长，超出部分固定等待30秒
+    let mut reconnect_attempt: usize = 0;
+
+    'reconnect: loop {
+        let adapter = match Adapter::default().await {
+            Some(adapter) => adapter,
+            None => {
+                reconnect_wait(&mut reconnect_attempt, &reconnecting).await;
+                continue 'reconnect;
+            }
+        };
+        if adapter.wait_available().await.is_err() {
+            reconnect_wait(&mut reconnect_attempt, &reconnecting).await;
+            continue 'reconnect;
+        }
+
+        let mut scan = match adapter.scan(&[]).await {
+            Ok(scan) => scan,
+            Err(_) => {
+                reconnect_wait(&mut reconnect_attempt, &reconnecting).await;
+                continue 'reconnect;
+            }
+        };
+
+        // 重新获得适配器并开始新一轮扫描：旧的GATT状态可能与新适配器不一致
+        // （例如上一轮的通知流在适配器掉线后挂起而不是报错退出，导致地址残留在两个集合里），
+        // 清空后让每个设备都能在本轮重新尝试GATT连接，同时广播兜底路径不会被残留状态挡住
+        gatt_connected.lock().unwrap().clear();
+        gatt_attempted.lock().unwrap().clear();
+
+        while let Some(discovered_device) = scan.next().await {
+            // 收到数据说明连接恢复正常，清零退避计数并撤销重连状态展示
+            reconnect_attempt = 0;
+            *reconnecting.lock().unwrap() = false;
+
+            let address = format!("{:?}", discovered_device.device.id());
+
+            // 每个设备只尝试一次GATT连接，成功后交由连接子系统持续更新
+            if gatt_attempted.lock().unwrap().insert(address.clone()) {
+                let adapter = adapter.clone();
+                let device = discovered_device.device.clone();
+                let name = device.name().unwrap_or(String::from("(未知)"));
+                let rssi = discovered_device.rssi.unwrap_or_default();
+                let hr_registry = heart_rate.clone();
+                let hr_events = event_senders.clone();
+                let hr_storage = storage.clone();
+                let hr_alarm = alarm_config.clone();
+                let hr_mqtt = mqtt.clone();
+                let gatt_connected = gatt_connected.clone();
+                let gatt_attempted = gatt_attempted.clone();
+                let hr_reconnecting = reconnecting.clone();
+                tokio::spawn(async move {
+                    let connected = gatt_connected.clone();
+                    let address = address.clone();
+                    let gatt_address = address.clone();
+                    let on_notification = move |rate: u8, name: &str, rssi: i16, battery: u8| {
+                        // GATT通知只在扫描流之外到来，单靠扫描循环清零重连标记覆盖不到纯GATT设备
+                        *hr_reconnecting.lock().unwrap() = false;
+                        let (payload, new_alarm, device_update) = {
+                            let mut registry = hr_registry.lock().unwrap();
+                            let new_alarm =
+                                registry.update(&gatt_address, rate, name, rssi, Some(battery), &hr_alarm);
+                            let device_update = registry
+                                .devices
+                                .get(&gatt_address)
+                                .map(|state| create_heart_rate_update(&gatt_address, state));
+                            (build_status_payload(&registry, *hr_reconnecting.lock().unwrap()), new_alarm, device_update)
+                        };
+                        broadcast_updates(&hr_events, &payload);
+                        record_reading(&hr_storage, &gatt_address, rate, rssi);
+                        if let Some(device_update) = &device_update {
+                            hr_mqtt.publish(&gatt_address, device_update);
+                        }
+                        if let Some(reason) = new_alarm {
+                            fire_webhook(&hr_alarm.webhook, &gatt_address, name, rate, &reason);
+                        }
+                    };
+                    if let Err(err) =
+                        connect_and_monitor(&adapter, device, name, rssi, &address, &connected, on_notification)
+                            .await
+                    {
+                        println!("GATT连接失败，回退到广播数据解析: {err}");
+                    }
+                    connected.lock().unwrap().remove(&address);
+                    // 连接子任务退出（正常断开或连接失败）后清除尝试标记，允许下次扫描重新发起GATT连接
+                    gatt_attempted.lock().unwrap().remove(&address);
+                });
+            }
+
+            // 尚未建立GATT连接的设备继续通过广播数据兜底
+            if !gatt_connected.lock().unwrap().contains(&address) {
+                let hr_registry = heart_rate.clone();
+                let hr_events = event_senders.clone();
+                let hr_storage = storage.clone();
+                let hr_alarm = alarm_config.clone();
+                let hr_mqtt = mqtt.clone();
+                let hr_reconnecting = reconnecting.clone();
+                handle_device(discovered_device, &filter, move |rate, name, rssi| {
+                    let (payload, new_alarm, device_update) = {
+                        let mut registry = hr_registry.lock().unwrap();
+                        let new_alarm = registry.update(&address, rate, name, rssi, None, &hr_alarm);
+                        let device_update = registry
+                            .devices
+                            .get(&address)
+                            .map(|state| create_heart_rate_update(&address, state));
+                        (build_status_payload(&registry, *hr_reconnecting.lock().unwrap()), new_alarm, device_update)
+                    };
+                    broadcast_updates(&hr_events, &payload);
+                    record_reading(&hr_storage, &address, rate, rssi);
+                    if let Some(device_update) = &device_update {
+                        hr_mqtt.publish(&address, device_update);
+                    }
+                    if let Some(reason) = new_alarm {
+                        fire_webhook(&hr_alarm.webhook, &address, name, rate, &reason);
+                    }
+                });
+            }
+        }
+
+        // 扫描流已结束（适配器掉线等），进入重连等待后重新获取适配器并重启扫描
+        println!("扫描流已中断，准备重连...");
+        reconnect_wait(&mut reconnect_attempt, &reconnecting).await;
     }
-    Ok(())
 }
 
-// 原始处理函数保持不变，添加回调参数
-fn handle_device<F>(discovered_device: AdvertisingDevice, callback: F) 
+// 按照[1s, 5s, 10s, 30s]的退避序列等待重连，超出部分固定等待30秒，并将状态标记为重连中
+async fn reconnect_wait(attempt: &mut usize, reconnecting: &ReconnectState) {
+    *reconnecting.lock().unwrap() = true;
+    let index = (*attempt).min(RECONNECT_BACKOFF_SECS.len() - 1);
+    let wait_secs = RECONNECT_BACKOFF_SECS[index];
+    println!("{}秒后尝试重新连接蓝牙适配器...", wait_secs);
+    tokio::time::sleep(std::time::Duration::from_secs(wait_secs)).await;
+    *attempt += 1;
+}
+
+// 将全部设备的最新心率数据推送给所有已连接的SSE客户端，写入失败的客户端视为已断开并移除
+fn broadcast_updates(event_senders: &EventSenders, payload: &StatusPayload) {
+    let json = serde_json::to_string(payload).unwrap();
+    let mut senders = event_senders.lock().unwrap();
+    senders.retain(|sender| sender.send(json.clone()).is_ok());
+}
+
+// 原始处理函数保持不变，过滤条件由ScanFilter提供
+fn handle_device<F>(discovered_device: AdvertisingDevice, filter: &ScanFilter, callback: F)
 where
     F: FnOnce(u8, &str, i16),
 {
     if let Some(manufacturer_data) = discovered_device.adv_data.manufacturer_data {
-        if manufacturer_data.company_id != 0x0157 {
-            return;
-        }
         let name = discovered_device
             .device
             .name()
             .unwrap_or(String::from("(未知)"));
-        if name != "Mi Smart Band 4" {
+        if !filter.matches(manufacturer_data.company_id, &name) {
             return;
         }
         let rssi = discovered_device.rssi.unwrap_or_default();
-        let heart_rate = manufacturer_data.data[3];
+        let Some(&heart_rate) = manufacturer_data.data.get(3) else {
+            return;
+        };
         println!("{name} ({rssi}dBm) 心率: {heart_rate:?}");
-        
+
         // 调用回调函数更新共享状态
         callback(heart_rate, &name, rssi);
     }
 }
 
-// 创建心率更新数据结构
-fn create_heart_rate_update(monitor: &HeartRateMonitor) -> HeartRateUpdate {
-    let elapsed_secs = monitor.last_update.elapsed().as_secs();
-    let status = if monitor.is_recent() { 
-        "实时更新中".to_string() 
-    } else { 
-        "信号丢失".to_string() 
+// 通过GATT连接读取标准心率服务：连接、发现服务、订阅心率测量通知，
+// 并在连接建立时读取一次电量。连接失败或服务/特征值缺失时返回Err，
+// 调用方应回退到广播数据解析。
+async fn connect_and_monitor<F>(
+    adapter: &Adapter,
+    device: Device,
+    name: String,
+    rssi: i16,
+    address: &str,
+    gatt_connected: &GattConnected,
+    callback: F,
+) -> Result<(), Box<dyn Error>>
+where
+    F: Fn(u8, &str, i16, u8),
+{
+    adapter.connect_device(&device).await?;
+
+    let hr_service = device
+        .discover_services_with_uuid(HEART_RATE_SERVICE)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or("设备未提供心率服务")?;
+
+    let measurement = hr_service
+        .discover_characteristics_with_uuid(HEART_RATE_MEASUREMENT)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or("设备未提供心率测量特征值")?;
+
+    let battery = read_battery_level(&device).await.unwrap_or(0);
+
+    // 服务和特征值都齐备，正式接管该设备，广播解析路径不再处理它
+    gatt_connected.lock().unwrap().insert(address.to_string());
+    println!("{name} 已通过GATT连接，电量: {battery}%");
+
+    let mut notifications = measurement.notify().await?;
+    // name/rssi在连接时只读取一次，广播路径那样的"实时"信号强度对GATT连接而言代价是一次异步读取，
+    // 因此在每次收到心率通知时顺带重新读取一次，读取失败则沿用上一次已知值，避免字段长期冻结
+    let mut last_name = name;
+    let mut last_rssi = rssi;
+    while let Some(Ok(payload)) = notifications.next().await {
+        if let Some(rate) = parse_heart_rate_measurement(&payload) {
+            if let Ok(fresh_rssi) = device.rssi().await {
+                last_rssi = fresh_rssi;
+            }
+            if let Ok(fresh_name) = device.name() {
+                last_name = fresh_name;
+            }
+            callback(rate, &last_name, last_rssi, battery);
+        }
+    }
+
+    Ok(())
+}
+
+// 读取电量服务(0x180F)的电量特征值(0x2A19)，仅在GATT连接建立时读取一次
+async fn read_battery_level(device: &Device) -> Option<u8> {
+    let service = device
+        .discover_services_with_uuid(BATTERY_SERVICE)
+        .await
+        .ok()?
+        .into_iter()
+        .next()?;
+    let characteristic = service
+        .discover_characteristics_with_uuid(BATTERY_LEVEL)
+        .await
+        .ok()?
+        .into_iter()
+        .next()?;
+    let value = characteristic.read().await.ok()?;
+    value.first().copied()
+}
+
+// 解析标准心率测量特征值(0x2A37)：flags字节bit0决定心率字段是uint8还是uint16，
+// 其余标志位（传感器接触状态、能量消耗等）当前不对外暴露，故不解析。
+fn parse_heart_rate_measurement(payload: &[u8]) -> Option<u8> {
+    let flags = *payload.first()?;
+    let is_uint16 = flags & 0x01 != 0;
+    if is_uint16 {
+        let rate = u16::from_le_bytes([*payload.get(1)?, *payload.get(2)?]);
+        Some(rate.min(u8::MAX as u16) as u8)
+    } else {
+        payload.get(1).copied()
+    }
+}
+
+// 打开（或创建）历史数据库并确保readings表存在
+fn open_storage() -> Result<Storage, Box<dyn Error>> {
+    let conn = Connection::open(HISTORY_DB_PATH)?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS readings (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            device_id TEXT NOT NULL,
+            bpm INTEGER NOT NULL,
+            rssi INTEGER NOT NULL,
+            timestamp INTEGER NOT NULL
+        )",
+        [],
+    )?;
+    Ok(Arc::new(Mutex::new(conn)))
+}
+
+// 将一次心率读数追加写入历史数据库，写入失败只记录日志不影响主流程
+fn record_reading(storage: &Storage, device_id: &str, bpm: u8, rssi: i16) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let conn = storage.lock().unwrap();
+    if let Err(err) = conn.execute(
+        "INSERT INTO readings (device_id, bpm, rssi, timestamp) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![device_id, bpm as i64, rssi as i64, timestamp],
+    ) {
+        println!("写入历史数据失败: {err}");
+    }
+}
+
+// 查询 [from, to] 区间内的历史读数，可选按设备过滤，超出下采样上限时按固定步长抽取
+fn query_history(storage: &Storage, device_id: Option<&str>, from: i64, to: i64) -> Vec<HistorySample> {
+    fn collect(
+        stmt: &mut rusqlite::Statement,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Vec<HistorySample> {
+        let rows = match stmt.query_map(params, |row| {
+            Ok(HistorySample {
+                device_id: row.get(0)?,
+                bpm: row.get::<_, i64>(1)? as u8,
+                rssi: row.get::<_, i64>(2)? as i16,
+                timestamp: row.get(3)?,
+            })
+        }) {
+            Ok(rows) => rows,
+            Err(_) => return Vec::new(),
+        };
+        rows.flatten().collect()
+    }
+
+    let conn = storage.lock().unwrap();
+    let samples = if let Some(device_id) = device_id {
+        match conn.prepare(
+            "SELECT device_id, bpm, rssi, timestamp FROM readings \
+             WHERE device_id = ?1 AND timestamp >= ?2 AND timestamp <= ?3 ORDER BY timestamp",
+        ) {
+            Ok(mut stmt) => collect(&mut stmt, rusqlite::params![device_id, from, to]),
+            Err(_) => Vec::new(),
+        }
+    } else {
+        match conn.prepare(
+            "SELECT device_id, bpm, rssi, timestamp FROM readings \
+             WHERE timestamp >= ?1 AND timestamp <= ?2 ORDER BY timestamp",
+        ) {
+            Ok(mut stmt) => collect(&mut stmt, rusqlite::params![from, to]),
+            Err(_) => Vec::new(),
+        }
+    };
+
+    downsample(samples, HISTORY_DOWNSAMPLE_CAP)
+}
+
+// 当样本数超过cap时按固定步长抽取，让图表在大范围查询下仍保持响应速度
+fn downsample(samples: Vec<HistorySample>, cap: usize) -> Vec<HistorySample> {
+    if samples.len() <= cap {
+        return samples;
+    }
+    let step = (samples.len() as f64 / cap as f64).ceil() as usize;
+    samples.into_iter().step_by(step.max(1)).collect()
+}
+
+// 解析形如 "/history?from=1&to=2&device=abc" 的查询字符串
+fn parse_query_params(url: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    if let Some((_, query)) = url.split_once('?') {
+        for pair in query.split('&') {
+            if let Some((key, value)) = pair.split_once('=') {
+                params.insert(percent_decode(key), percent_decode(value));
+            }
+        }
+    }
+    params
+}
+
+// 简易的URL百分号解码，足以覆盖时间戳与设备ID这类ASCII取值
+fn percent_decode(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '+' => result.push(' '),
+            '%' => match (chars.next(), chars.next()) {
+                (Some(hi), Some(lo)) => match u8::from_str_radix(&format!("{hi}{lo}"), 16) {
+                    Ok(byte) => result.push(byte as char),
+                    Err(_) => result.push('%'),
+                },
+                _ => result.push('%'),
+            },
+            other => result.push(other),
+        }
+    }
+    result
+}
+
+// 创建单个设备的心率更新数据结构
+fn create_heart_rate_update(device_id: &str, state: &DeviceState) -> HeartRateUpdate {
+    let elapsed_secs = state.last_update.elapsed().as_secs();
+    let status = if state.is_recent() {
+        "实时更新中".to_string()
+    } else {
+        "信号丢失".to_string()
     };
-    
-    let status_color = if monitor.is_recent() { 
-        "#27ae60".to_string() 
-    } else { 
-        "#e74c3c".to_string() 
+
+    let status_color = if state.is_recent() {
+        "#27ae60".to_string()
+    } else {
+        "#e74c3c".to_string()
     };
-    
+
     HeartRateUpdate {
-        heart_rate: monitor.current_rate,
-        device_name: monitor.device_name.clone(),
-        rssi: monitor.rssi,
+        device_id: device_id.to_string(),
+        heart_rate: state.current_rate,
+        device_name: state.device_name.clone(),
+        rssi: state.rssi,
+        battery: state.battery,
         elapsed_secs,
         status,
         status_color,
-        history: monitor.history.iter().cloned().collect(),
+        history: state.history.iter().cloned().collect(),
+        alarm: state.in_alarm,
+        alarm_reason: state.alarm_reason.clone(),
     }
 }
 
+// 创建注册表中所有设备的心率更新数据结构，供 /data 与 /events 共用
+fn create_all_updates(registry: &DeviceRegistry) -> Vec<HeartRateUpdate> {
+    registry
+        .devices
+        .iter()
+        .map(|(id, state)| create_heart_rate_update(id, state))
+        .collect()
+}
+
+// 处于重连状态时，用统一的提示覆盖每个设备的status/status_color，让前端感知适配器正在恢复；
+// 跳过仍在is_recent()窗口内的设备（例如正通过GATT持续收到通知的设备），避免覆盖掉正常更新中的状态
+fn apply_reconnect_status(updates: &mut [HeartRateUpdate], reconnecting: bool) {
+    if !reconnecting {
+        return;
+    }
+    for update in updates.iter_mut() {
+        if update.elapsed_secs < DEVICE_RECENT_SECS {
+            continue;
+        }
+        update.status = "重连中…".to_string();
+        update.status_color = "#f39c12".to_string();
+    }
+}
+
+// 组装一次完整的状态负载：设备数组叠加per-device的重连覆盖，外加独立的顶层reconnecting标记，
+// 供 /data、/events 初始快照与SSE推送统一使用
+fn build_status_payload(registry: &DeviceRegistry, reconnecting: bool) -> StatusPayload {
+    let mut devices = create_all_updates(registry);
+    apply_reconnect_status(&mut devices, reconnecting);
+    StatusPayload { reconnecting, devices }
+}
+
 // HTTP服务器实现
-fn start_http_server(heart_rate: Arc<Mutex<HeartRateMonitor>>) {
+fn start_http_server(
+    heart_rate: Arc<Mutex<DeviceRegistry>>,
+    event_senders: EventSenders,
+    history: Storage,
+    reconnecting: ReconnectState,
+) {
     let addr = "0.0.0.0:8080";
     let server = Server::http(addr).expect("无法启动HTTP服务器");
-    
+
     // 创建HTML内容类型头
     let html_content_type = Header::from_bytes(&b"Content-Type"[..], &b"text/html; charset=utf-8"[..])
         .expect("创建内容类型头失败");
-    
+
     // 创建JSON内容类型头
     let json_content_type = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
         .expect("创建内容类型头失败");
 
     for request in server.incoming_requests() {
-        // 处理数据端点
+        // 处理数据端点（轮询方式，为兼容旧前端保留），返回所有设备的状态数组
         if request.url() == "/data" {
-            let monitor = heart_rate.lock().unwrap();
-            let update = create_heart_rate_update(&monitor);
-            let json = serde_json::to_string(&update).unwrap();
-            
+            let registry = heart_rate.lock().unwrap();
+            let payload = build_status_payload(&registry, *reconnecting.lock().unwrap());
+            let json = serde_json::to_string(&payload).unwrap();
+
             let response = Response::from_string(json)
                 .with_header(json_content_type.clone());
-            
+
             request.respond(response).expect("响应请求失败");
             continue;
         }
-        
+
+        // 处理历史查询端点：/history?from=<unix>&to=<unix>&device=<id>
+        if request.url().starts_with("/history") {
+            let params = parse_query_params(request.url());
+            let from = params.get("from").and_then(|v| v.parse::<i64>().ok()).unwrap_or(0);
+            let to = params.get("to").and_then(|v| v.parse::<i64>().ok()).unwrap_or(i64::MAX);
+            let device = params.get("device").map(String::as_str);
+
+            let samples = query_history(&history, device, from, to);
+            let json = serde_json::to_string(&samples).unwrap();
+
+            let response = Response::from_string(json)
+                .with_header(json_content_type.clone());
+
+            request.respond(response).expect("响应请求失败");
+            continue;
+        }
+
+        // 处理SSE推送端点：接管连接的Writer，长期持有并在有新数据时推送
+        if request.url() == "/events" {
+            let (sender, receiver) = mpsc::channel::<String>();
+
+            // 先把当前状态推送一次，避免客户端订阅后要等下一次心率更新才有画面
+            {
+                let registry = heart_rate.lock().unwrap();
+                let payload = build_status_payload(&registry, *reconnecting.lock().unwrap());
+                let _ = sender.send(serde_json::to_string(&payload).unwrap());
+            }
+            event_senders.lock().unwrap().push(sender);
+
+            thread::spawn(move || {
+                let mut writer = request.into_writer();
+                let head = b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+                if writer.write_all(head).is_err() {
+                    return;
+                }
+
+                for payload in receiver {
+                    let frame = format!("data: {payload}\n\n");
+                    if writer.write_all(frame.as_bytes()).is_err() {
+                        break;
+                    }
+                    if writer.flush().is_err() {
+                        break;
+                    }
+                }
+            });
+            continue;
+        }
+
         // 主页面
         let html = r#"
         <!DOCTYPE html>
@@ -175,7 +967,7 @@ fn start_http_server(heart_rate: Arc<Mutex<HeartRateMonitor>>) {
         <head>
             <meta charset="UTF-8">
             <meta name="viewport" content="width=device-width, initial-scale=1.0">
-            <title>小米手环4 心率监测</title>
+            <title>心率监测</title>
             <script src="https://cdn.jsdelivr.net/npm/chart.js"></script>
             <style>
                 * {
@@ -237,6 +1029,13 @@ fn start_http_server(heart_rate: Arc<Mutex<HeartRateMonitor>>) {
                     flex: 1;
                     min-width: 200px;
                 }
+                .device-card.alarm .heart-rate-display {
+                    animation: alarm-flash 1s infinite;
+                }
+                @keyframes alarm-flash {
+                    0%, 100% { color: #ff6b6b; text-shadow: 0 0 20px rgba(255, 107, 107, 0.7); }
+                    50% { color: #ff1744; text-shadow: 0 0 30px rgba(255, 23, 68, 0.9); }
+                }
                 .bpm {
                     font-size: 24px;
                     color: #a9a9a9;
@@ -268,6 +1067,54 @@ fn start_http_server(heart_rate: Arc<Mutex<HeartRateMonitor>>) {
                     height: 350px;
                     margin-top: 20px;
                 }
+                .device-card {
+                    margin-bottom: 30px;
+                    padding-bottom: 30px;
+                    border-bottom: 1px solid rgba(255, 255, 255, 0.1);
+                }
+                .device-card:last-child {
+                    margin-bottom: 0;
+                    padding-bottom: 0;
+                    border-bottom: none;
+                }
+                .history-panel {
+                    margin-top: 30px;
+                }
+                .history-panel h2 {
+                    font-size: 20px;
+                    margin-bottom: 15px;
+                }
+                .history-controls {
+                    display: flex;
+                    flex-wrap: wrap;
+                    gap: 15px;
+                    align-items: flex-end;
+                    margin-bottom: 15px;
+                }
+                .history-controls label {
+                    display: flex;
+                    flex-direction: column;
+                    font-size: 13px;
+                    color: #ccc;
+                    gap: 5px;
+                }
+                .history-controls select,
+                .history-controls input {
+                    padding: 6px 8px;
+                    border-radius: 8px;
+                    border: none;
+                    background: rgba(255, 255, 255, 0.15);
+                    color: #fff;
+                }
+                .history-controls button {
+                    padding: 8px 18px;
+                    border-radius: 8px;
+                    border: none;
+                    background: #ff6b6b;
+                    color: #fff;
+                    font-weight: 600;
+                    cursor: pointer;
+                }
                 .footer {
                     margin-top: 25px;
                     text-align: center;
@@ -286,48 +1133,108 @@ fn start_http_server(heart_rate: Arc<Mutex<HeartRateMonitor>>) {
         </head>
         <body>
             <div class="container">
-                <h1>小米手环4 实时心率监测</h1>
-                
-                <div class="status-header">
-                    <div class="heart-rate-display">
-                        <span id="heart-rate">0</span>
-                        <div class="bpm">BPM</div>
+                <h1>实时心率监测</h1>
+
+                <div id="devices">
+                    <div class="device-card-placeholder">正在搜索心率设备...</div>
+                </div>
+
+                <div class="history-panel">
+                    <h2>历史查询</h2>
+                    <div class="history-controls">
+                        <label>设备
+                            <select id="history-device"><option value="">全部设备</option></select>
+                        </label>
+                        <label>开始时间
+                            <input type="datetime-local" id="history-from">
+                        </label>
+                        <label>结束时间
+                            <input type="datetime-local" id="history-to">
+                        </label>
+                        <button id="history-query">查询</button>
                     </div>
-                    
-                    <div class="device-info">
-                        <div class="info-item">
-                            <span class="info-label">设备名称:</span>
-                            <span class="info-value" id="device-name">未知</span>
-                        </div>
-                        <div class="info-item">
-                            <span class="info-label">信号强度:</span>
-                            <span class="info-value" id="rssi">- dBm</span>
-                        </div>
-                        <div class="info-item">
-                            <span class="info-label">最后更新:</span>
-                            <span class="info-value" id="last-update">- 秒前</span>
-                        </div>
-                        <div class="info-item">
-                            <span class="info-label">当前状态:</span>
-                            <span class="info-value" id="status">等待数据...</span>
-                        </div>
+                    <div class="chart-container">
+                        <canvas id="historyChart"></canvas>
                     </div>
                 </div>
-                
-                <div class="chart-container">
-                    <canvas id="heartRateChart"></canvas>
-                </div>
-                
+
                 <div class="footer">
-                    实时数据更新 | 小米手环4心率监测系统
+                    实时数据更新 | 心率监测系统
                 </div>
             </div>
 
             <script>
-                // 初始化图表
-                function initChart() {
-                    const ctx = document.getElementById('heartRateChart').getContext('2d');
-                    window.heartRateChart = new Chart(ctx, {
+                // 每个设备对应的DOM元素与图表实例
+                const deviceCards = new Map();
+
+                // 根据设备ID创建一张心率卡片（首次出现时），返回缓存的DOM/图表引用
+                function ensureDeviceCard(deviceId) {
+                    if (deviceCards.has(deviceId)) {
+                        return deviceCards.get(deviceId);
+                    }
+
+                    const placeholder = document.querySelector('.device-card-placeholder');
+                    if (placeholder) {
+                        placeholder.remove();
+                    }
+
+                    const card = document.createElement('div');
+                    card.className = 'device-card';
+                    card.innerHTML = `
+                        <div class="status-header">
+                            <div class="heart-rate-display">
+                                <span class="heart-rate-value">0</span>
+                                <div class="bpm">BPM</div>
+                            </div>
+                            <div class="device-info">
+                                <div class="info-item">
+                                    <span class="info-label">设备名称:</span>
+                                    <span class="info-value device-name">未知</span>
+                                </div>
+                                <div class="info-item">
+                                    <span class="info-label">信号强度:</span>
+                                    <span class="info-value rssi">- dBm</span>
+                                </div>
+                                <div class="info-item">
+                                    <span class="info-label">电量:</span>
+                                    <span class="info-value battery">- %</span>
+                                </div>
+                                <div class="info-item">
+                                    <span class="info-label">最后更新:</span>
+                                    <span class="info-value last-update">- 秒前</span>
+                                </div>
+                                <div class="info-item">
+                                    <span class="info-label">当前状态:</span>
+                                    <span class="info-value status">等待数据...</span>
+                                </div>
+                            </div>
+                        </div>
+                        <div class="chart-container">
+                            <canvas></canvas>
+                        </div>
+                    `;
+                    document.getElementById('devices').appendChild(card);
+
+                    const entry = {
+                        card,
+                        chart: initChart(card.querySelector('canvas')),
+                        elements: {
+                            heartRate: card.querySelector('.heart-rate-value'),
+                            deviceName: card.querySelector('.device-name'),
+                            rssi: card.querySelector('.rssi'),
+                            battery: card.querySelector('.battery'),
+                            lastUpdate: card.querySelector('.last-update'),
+                            status: card.querySelector('.status'),
+                        },
+                    };
+                    deviceCards.set(deviceId, entry);
+                    return entry;
+                }
+
+                // 为一张卡片的画布初始化图表
+                function initChart(canvas) {
+                    const ctx = canvas.getContext('2d');
+                    return new Chart(ctx, {
                         type: 'line',
                         data: {
                             datasets: [{
@@ -401,48 +1308,72 @@ fn start_http_server(heart_rate: Arc<Mutex<HeartRateMonitor>>) {
                     });
                 }
                 
-                // 更新UI函数
-                function updateUI(data) {
-                    // 更新心率显示
-                    document.getElementById('heart-rate').textContent = data.heart_rate;
-                    
-                    // 更新设备名称
-                    document.getElementById('device-name').textContent = data.device_name;
-                    
-                    // 更新RSSI
-                    document.getElementById('rssi').textContent = data.rssi + ' dBm';
-                    
-                    // 更新最后更新时间
-                    document.getElementById('last-update').textContent = data.elapsed_secs + '秒前';
-                    
-                    // 更新状态
-                    const statusElement = document.getElementById('status');
-                    statusElement.textContent = data.status;
-                    statusElement.style.color = data.status_color;
-                    
-                    // 更新图表
-                    updateChart(data.history);
+                // 把新出现的设备ID加入历史查询的设备下拉框
+                function refreshHistoryDeviceOptions(deviceId) {
+                    const select = document.getElementById('history-device');
+                    const alreadyListed = Array.from(select.options).some((opt) => opt.value === deviceId);
+                    if (!alreadyListed) {
+                        const option = document.createElement('option');
+                        option.value = deviceId;
+                        option.textContent = deviceId;
+                        select.appendChild(option);
+                    }
                 }
-                
-                // 更新图表
-                function updateChart(history) {
-                    if (!window.heartRateChart) {
-                        initChart();
+
+                // 更新UI函数：payload.reconnecting独立于设备数组存在，
+                // 即使还没有发现任何设备也能让占位提示反映适配器正在重连
+                function updateUI(payload) {
+                    const placeholder = document.querySelector('.device-card-placeholder');
+                    if (placeholder) {
+                        placeholder.textContent = payload.reconnecting ? '重连中…' : '正在搜索心率设备...';
                     }
-                    
+
+                    payload.devices.forEach((data) => {
+                        const entry = ensureDeviceCard(data.device_id);
+                        refreshHistoryDeviceOptions(data.device_id);
+
+                        // 更新心率显示
+                        entry.elements.heartRate.textContent = data.heart_rate;
+
+                        // 更新设备名称
+                        entry.elements.deviceName.textContent = data.device_name;
+
+                        // 更新RSSI
+                        entry.elements.rssi.textContent = data.rssi + ' dBm';
+
+                        // 更新电量
+                        entry.elements.battery.textContent = data.battery + ' %';
+
+                        // 更新最后更新时间
+                        entry.elements.lastUpdate.textContent = data.elapsed_secs + '秒前';
+
+                        // 更新状态，报警时附加报警原因并让心率显示闪烁提醒
+                        entry.elements.status.textContent = data.alarm
+                            ? `${data.status} · ${data.alarm_reason}`
+                            : data.status;
+                        entry.elements.status.style.color = data.alarm ? '#ff1744' : data.status_color;
+                        entry.card.classList.toggle('alarm', data.alarm);
+
+                        // 更新图表
+                        updateChart(entry.chart, data.history);
+                    });
+                }
+
+                // 更新图表
+                function updateChart(chart, history) {
                     // 创建正确的数据点数组
                     const newData = [];
                     const startIndex = Math.max(0, history.length - 60);
-                    
+
                     for (let i = startIndex; i < history.length; i++) {
                         // 计算正确的x轴位置（从0到59）
                         const x = 59 - (history.length - 1 - i);
-                        newData.push({ 
-                            x: x, 
+                        newData.push({
+                            x: x,
                             y: history[i]
                         });
                     }
-                    
+
                     // 如果数据不足60个，在前面填充空点
                     if (newData.length < 60) {
                         const emptyPoints = 60 - newData.length;
@@ -450,34 +1381,98 @@ fn start_http_server(heart_rate: Arc<Mutex<HeartRateMonitor>>) {
                             newData.unshift({ x: i, y: null });
                         }
                     }
-                    
-                    window.heartRateChart.data.datasets[0].data = newData;
-                    window.heartRateChart.update('none');
+
+                    chart.data.datasets[0].data = newData;
+                    chart.update('none');
                 }
-                
-                // 获取最新心率数据
-                async function fetchHeartRateData() {
+
+
+                // 通过SSE订阅心率更新，浏览器会在断线后自动重连
+                function subscribeHeartRateEvents() {
+                    const source = new EventSource('/events');
+                    source.onmessage = (event) => {
+                        updateUI(JSON.parse(event.data));
+                    };
+                    source.onerror = () => {
+                        deviceCards.forEach((entry) => {
+                            entry.elements.status.textContent = '连接已断开，重连中...';
+                        });
+                    };
+                }
+
+                // 订阅实时心率推送
+                subscribeHeartRateEvents();
+
+                // 历史查询图表，懒加载：首次查询时才创建
+                let historyChart = null;
+
+                function renderHistoryChart(samples) {
+                    const points = samples.map((sample) => ({ x: sample.timestamp, y: sample.bpm }));
+                    if (!historyChart) {
+                        const ctx = document.getElementById('historyChart').getContext('2d');
+                        historyChart = new Chart(ctx, {
+                            type: 'line',
+                            data: {
+                                datasets: [{
+                                    label: '历史心率 (BPM)',
+                                    data: points,
+                                    borderColor: '#ff6b6b',
+                                    backgroundColor: 'rgba(255, 107, 107, 0.1)',
+                                    borderWidth: 2,
+                                    pointRadius: 2,
+                                    tension: 0.3,
+                                    fill: true
+                                }]
+                            },
+                            options: {
+                                responsive: true,
+                                maintainAspectRatio: false,
+                                scales: {
+                                    x: {
+                                        type: 'linear',
+                                        grid: { color: 'rgba(255, 255, 255, 0.1)' },
+                                        ticks: {
+                                            color: '#ccc',
+                                            callback: (value) => new Date(value * 1000).toLocaleTimeString()
+                                        }
+                                    },
+                                    y: {
+                                        grid: { color: 'rgba(255, 255, 255, 0.1)' },
+                                        ticks: { color: '#ccc' },
+                                        title: { display: true, text: '心率 (BPM)', color: '#ccc' }
+                                    }
+                                },
+                                plugins: {
+                                    legend: { labels: { color: '#ccc' } }
+                                }
+                            }
+                        });
+                    } else {
+                        historyChart.data.datasets[0].data = points;
+                        historyChart.update();
+                    }
+                }
+
+                document.getElementById('history-query').addEventListener('click', async () => {
+                    const device = document.getElementById('history-device').value;
+                    const fromInput = document.getElementById('history-from').value;
+                    const toInput = document.getElementById('history-to').value;
+                    const from = fromInput ? Math.floor(new Date(fromInput).getTime() / 1000) : 0;
+                    const to = toInput ? Math.floor(new Date(toInput).getTime() / 1000) : Math.floor(Date.now() / 1000);
+
+                    const params = new URLSearchParams({ from, to });
+                    if (device) {
+                        params.set('device', device);
+                    }
+
                     try {
-                        const response = await fetch('/data');
-                        if (!response.ok) {
-                            throw new Error('网络响应异常');
-                        }
-                        const data = await response.json();
-                        updateUI(data);
+                        const response = await fetch('/history?' + params.toString());
+                        const samples = await response.json();
+                        renderHistoryChart(samples);
                     } catch (error) {
-                        console.error('获取数据失败:', error);
-                        document.getElementById('status').textContent = '数据获取失败';
+                        console.error('查询历史数据失败:', error);
                     }
-                }
-                
-                // 初始化图表
-                initChart();
-                
-                // 立即获取数据
-                fetchHeartRateData();
-                
-                // 每2秒获取一次数据
-                setInterval(fetchHeartRateData, 2000);
+                });
             </script>
         </body>
         </html>